@@ -0,0 +1,94 @@
+use bitcoin::{Address, AddressType, PrivateKey, PublicKey};
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u64; 5] = [
+    0xf5dee51989,
+    0xa9fdca3312,
+    0x1bab10e32d,
+    0x3706b1677a,
+    0x644d626ffd,
+];
+
+/// A BIP380 single-key output descriptor (`wpkh(...)`, `pkh(...)`, or `tr(...)`) for a derived
+/// stealth address, in its private-key form when `private_key` is given.
+pub fn for_key(
+    address_type: AddressType,
+    public_key: &PublicKey,
+    private_key: Option<&PrivateKey>,
+) -> String {
+    let key = match private_key {
+        Some(private_key) => private_key.to_string(),
+        None => public_key.to_string(),
+    };
+
+    let body = match address_type {
+        AddressType::P2wpkh => format!("wpkh({key})"),
+        AddressType::P2pkh => format!("pkh({key})"),
+        AddressType::P2tr => format!("tr({key})"),
+        _ => unreachable!("privpay only derives p2pkh/p2wpkh/p2tr addresses"),
+    };
+
+    with_checksum(&body)
+}
+
+/// A BIP380 `addr(...)` descriptor for a stealth address, used on the sender side where no key
+/// material is available, only the derived address itself.
+pub fn for_address(address: &Address) -> String {
+    with_checksum(&format!("addr({address})"))
+}
+
+/// Append the BIP380 checksum to `descriptor`.
+fn with_checksum(descriptor: &str) -> String {
+    let mut symbols = expand(descriptor);
+    symbols.extend([0u64; 8]);
+    let checksum = polymod(&symbols) ^ 1;
+
+    let checksum: String = (0..8)
+        .map(|i| CHECKSUM_CHARSET[((checksum >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect();
+
+    format!("{descriptor}#{checksum}")
+}
+
+fn expand(s: &str) -> Vec<u64> {
+    let mut groups = Vec::new();
+    let mut symbols = Vec::new();
+
+    for c in s.chars() {
+        let v = INPUT_CHARSET
+            .find(c)
+            .expect("descriptor only uses the BIP380 input charset") as u64;
+        symbols.push(v & 31);
+        groups.push(v >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+
+    match groups.len() {
+        1 => symbols.push(groups[0]),
+        2 => symbols.push(groups[0] * 3 + groups[1]),
+        _ => {}
+    }
+
+    symbols
+}
+
+fn polymod(symbols: &[u64]) -> u64 {
+    let mut checksum: u64 = 1;
+
+    for &value in symbols {
+        let top = checksum >> 35;
+        checksum = ((checksum & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+
+    checksum
+}
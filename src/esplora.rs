@@ -0,0 +1,123 @@
+use bitcoin::Address;
+use clap::Args;
+
+use crate::Error;
+
+/// Optional Esplora/electrs REST endpoint used to report what a derived address actually holds
+#[derive(Debug, Clone, Args)]
+pub struct EsploraArgs {
+    /// Esplora-style REST API to query for balances and UTXOs, e.g. an electrs instance
+    #[arg(long)]
+    scan_endpoint: Option<String>,
+    /// Stop deriving addresses after this many consecutive unused indices, instead of requiring -f
+    #[arg(long, requires = "scan_endpoint")]
+    gap_limit: Option<u32>,
+}
+
+impl EsploraArgs {
+    pub fn gap_limit(&self) -> Option<u32> {
+        self.gap_limit
+    }
+
+    /// Query `scan_endpoint` for `address`'s balance and UTXO set. Returns `None` when no
+    /// endpoint was configured.
+    pub fn lookup(&self, address: &Address) -> Result<Option<Lookup>, Error> {
+        let Some(endpoint) = &self.scan_endpoint else {
+            return Ok(None);
+        };
+
+        let stats = get_json(&format!("{endpoint}/address/{address}"))?;
+
+        let confirmed_sat = stats["chain_stats"]["funded_txo_sum"].as_i64().unwrap_or(0)
+            - stats["chain_stats"]["spent_txo_sum"].as_i64().unwrap_or(0);
+        let unconfirmed_sat = stats["mempool_stats"]["funded_txo_sum"]
+            .as_i64()
+            .unwrap_or(0)
+            - stats["mempool_stats"]["spent_txo_sum"]
+                .as_i64()
+                .unwrap_or(0);
+        let ever_funded = stats["chain_stats"]["tx_count"].as_u64().unwrap_or(0) > 0
+            || stats["mempool_stats"]["tx_count"].as_u64().unwrap_or(0) > 0;
+
+        let utxos = get_json(&format!("{endpoint}/address/{address}/utxo"))?
+            .members()
+            .map(|utxo| Utxo {
+                txid: utxo["txid"].to_string(),
+                vout: utxo["vout"].as_u32().unwrap_or(0),
+                value_sat: utxo["value"].as_u64().unwrap_or(0),
+                height: utxo["status"]["block_height"].as_u32(),
+            })
+            .collect();
+
+        Ok(Some(Lookup {
+            confirmed_sat,
+            unconfirmed_sat,
+            ever_funded,
+            utxos,
+        }))
+    }
+}
+
+fn get_json(url: &str) -> Result<json::JsonValue, Error> {
+    let body = reqwest::blocking::get(url)?.text()?;
+    json::parse(&body).map_err(Error::Json)
+}
+
+/// The confirmed/unconfirmed balance and UTXO set of a single address
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    pub confirmed_sat: i64,
+    pub unconfirmed_sat: i64,
+    ever_funded: bool,
+    pub utxos: Vec<Utxo>,
+}
+
+impl Lookup {
+    /// Whether this address has ever appeared in a transaction, used to drive gap-limit
+    /// derivation. Based on `tx_count`, not the current balance, so an address that was funded
+    /// and later fully spent (e.g. already swept) still counts as used instead of looking
+    /// untouched.
+    pub fn is_used(&self) -> bool {
+        self.ever_funded
+    }
+
+    /// Add `balance_sat`, `unconfirmed_sat` and `utxos` fields to an existing address entry
+    pub fn merge_into(&self, entry: &mut json::JsonValue) {
+        entry["balance_sat"] = self.confirmed_sat.into();
+        entry["unconfirmed_sat"] = self.unconfirmed_sat.into();
+        entry["utxos"] = self
+            .utxos
+            .iter()
+            .map(Utxo::to_json)
+            .collect::<Vec<_>>()
+            .into();
+    }
+
+    pub fn to_plain(&self) -> String {
+        format!(
+            "{} sat ({} unconfirmed, {} utxos)",
+            self.confirmed_sat,
+            self.unconfirmed_sat,
+            self.utxos.len()
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Utxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value_sat: u64,
+    pub height: Option<u32>,
+}
+
+impl Utxo {
+    fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            txid: self.txid.clone(),
+            vout: self.vout,
+            value_sat: self.value_sat,
+            height: self.height,
+        }
+    }
+}
@@ -1,13 +1,23 @@
+mod descriptor;
+mod esplora;
+mod mnemonic;
+mod psbt;
+mod scan;
+mod sweep;
+
 use std::collections::HashSet;
 use std::ops::RangeInclusive;
 use std::str::FromStr;
 
 use bip351::*;
 use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::secp256k1::{All, Secp256k1};
 use bitcoin::{Network, Script};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use dialoguer::Password;
+use scan::{RpcArgs, ScanRangeArgs};
 use secstr::SecUtf8;
+use sweep::SweepArgs;
 
 /// Private Payments (BIP351) Helper Tool
 ///
@@ -57,9 +67,45 @@ enum Receiver {
         address_types: AddressTypesArg,
         #[command(flatten)]
         address_range: AddressRangeArgs,
+        #[command(flatten)]
+        esplora: esplora::EsploraArgs,
         /// Show the private key for each generated address
         #[arg(short = 'P', default_value_t = false)]
         show_private_key: bool,
+        /// Emit a BIP380 output descriptor for each generated address
+        #[arg(long, default_value_t = false)]
+        descriptor: bool,
+    },
+    /// Scan a range of blocks via bitcoind RPC for notifications
+    Scan {
+        #[command(flatten)]
+        rpc: RpcArgs,
+        #[command(flatten)]
+        scan_range: ScanRangeArgs,
+        #[command(flatten)]
+        common_args: CommonArgs,
+        #[command(flatten)]
+        address_types: AddressTypesArg,
+        #[command(flatten)]
+        address_range: AddressRangeArgs,
+        /// Show the private key for each generated address
+        #[arg(short = 'P', default_value_t = false)]
+        show_private_key: bool,
+    },
+    /// Sign a transaction spending detected stealth UTXOs
+    Sweep {
+        /// The notification payload
+        notification: String,
+        #[command(flatten)]
+        common_args: CommonArgs,
+        #[command(flatten)]
+        address_types: AddressTypesArg,
+        #[command(flatten)]
+        address_range: AddressRangeArgs,
+        #[command(flatten)]
+        esplora: esplora::EsploraArgs,
+        #[command(flatten)]
+        sweep: SweepArgs,
     },
 }
 
@@ -78,6 +124,13 @@ enum Sender {
         recipient_payment_code: String,
         #[command(flatten)]
         address_range: AddressRangeArgs,
+        #[command(flatten)]
+        esplora: esplora::EsploraArgs,
+        #[command(flatten)]
+        psbt: psbt::PsbtArgs,
+        /// Emit a BIP380 output descriptor for each generated address
+        #[arg(long, default_value_t = false)]
+        descriptor: bool,
     },
 }
 
@@ -89,6 +142,39 @@ struct CommonArgs {
     /// Output results as JSON
     #[arg(long, default_value_t = false)]
     json: bool,
+    /// How the wallet seed is provided
+    #[arg(long, value_enum, rename_all = "lower", default_value_t = SeedSource::default())]
+    seed_source: SeedSource,
+    /// Which Bitcoin network to operate on
+    #[arg(long, value_enum, rename_all = "lower", default_value_t = NetworkArg::default())]
+    network: NetworkArg,
+}
+
+#[derive(Debug, Clone, Default, ValueEnum)]
+enum SeedSource {
+    #[default]
+    Hex,
+    Mnemonic,
+}
+
+#[derive(Debug, Clone, Default, ValueEnum)]
+enum NetworkArg {
+    #[default]
+    Bitcoin,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl From<NetworkArg> for Network {
+    fn from(network: NetworkArg) -> Self {
+        match network {
+            NetworkArg::Bitcoin => Self::Bitcoin,
+            NetworkArg::Testnet => Self::Testnet,
+            NetworkArg::Signet => Self::Signet,
+            NetworkArg::Regtest => Self::Regtest,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Args)]
@@ -130,10 +216,16 @@ impl Receiver {
 
         match self {
             Receiver::Code {
-                common_args: CommonArgs { account, json },
+                common_args:
+                    CommonArgs {
+                        account,
+                        json,
+                        seed_source,
+                        network,
+                    },
                 address_types: AddressTypesArg { address_types },
             } => {
-                let seed = get_seed_hex()?;
+                let seed = get_seed_hex(&seed_source)?;
 
                 let accepted_addresses: HashSet<bip351::AddressType> =
                     address_types.into_iter().map(|t| t.into()).collect();
@@ -141,7 +233,7 @@ impl Receiver {
                 let recipient = bip351::Recipient::from_seed(
                     &secp,
                     &seed,
-                    Network::Bitcoin,
+                    network.into(),
                     account,
                     accepted_addresses,
                 )?;
@@ -169,14 +261,18 @@ impl Receiver {
             }
             Receiver::Decode {
                 notification,
-                common_args: CommonArgs { account, json },
-                address_types: AddressTypesArg { address_types },
-                address_range:
-                    AddressRangeArgs {
-                        start_address_index,
-                        last_address_index,
+                common_args:
+                    CommonArgs {
+                        account,
+                        json,
+                        seed_source,
+                        network,
                     },
+                address_types: AddressTypesArg { address_types },
+                address_range,
+                esplora,
                 show_private_key,
+                descriptor: show_descriptor,
             } => {
                 let bytes: Vec<u8> = FromHex::from_hex(&notification)?;
                 let script = if bytes.starts_with(b"PP") {
@@ -185,7 +281,7 @@ impl Receiver {
                     Script::from(bytes)
                 };
 
-                let seed = get_seed_hex()?;
+                let seed = get_seed_hex(&seed_source)?;
 
                 let accepted_addresses: HashSet<bip351::AddressType> =
                     address_types.into_iter().map(|t| t.into()).collect();
@@ -193,38 +289,24 @@ impl Receiver {
                 let recipient = bip351::Recipient::from_seed(
                     &secp,
                     &seed,
-                    Network::Bitcoin,
+                    network.into(),
                     account,
                     accepted_addresses,
                 )?;
 
                 if let Some(commitment) = recipient.detect_notification(&secp, &script) {
-                    let range = index_range(start_address_index, last_address_index);
-                    let output_capacity =
-                        range.end().saturating_add(1).saturating_sub(*range.start()) as usize;
+                    let mut derived = decode_addresses(
+                        &secp,
+                        &recipient,
+                        &commitment,
+                        &address_range,
+                        &esplora,
+                        show_private_key,
+                        show_descriptor,
+                    )?;
 
                     if json {
-                        let mut addresses: Vec<json::JsonValue> =
-                            Vec::with_capacity(output_capacity);
-                        for c in range {
-                            let (address, public_key, private_key) =
-                                recipient.key_info(&secp, &commitment, c)?;
-                            if show_private_key {
-                                addresses.push(json::object! {
-                                    address: address.to_string(),
-                                    index: c,
-                                    public_key: public_key.to_string(),
-                                    private_key: private_key.to_string(),
-                                });
-                            } else {
-                                addresses.push(json::object! {
-                                    address: address.to_string(),
-                                    index: c,
-                                });
-                            }
-                        }
-
-                        let output = json::object! {
+                        let mut output = json::object! {
                             receiver: json::object! {
                                 payment_code: recipient.payment_code().to_string(),
                                 account: account,
@@ -235,30 +317,254 @@ impl Receiver {
                                 payload: script.to_bytes()[2..].to_hex(),
                                 asm: script.asm(),
                             },
-                            addresses: addresses,
+                            addresses: derived.addresses,
                         };
 
-                        return Ok(output.into());
-                    } else {
-                        let mut lines: Vec<String> = Vec::with_capacity(output_capacity);
-                        for c in range {
-                            let (address, public_key, private_key) =
-                                recipient.key_info(&secp, &commitment, c)?;
-                            if show_private_key {
-                                lines.push(format!("{c}: {address} {public_key} {private_key}"));
-                            } else {
-                                lines.push(format!("{c}: {address}"));
-                            }
+                        if !derived.descriptors.is_empty() {
+                            output["descriptors"] = derived.descriptors.into();
                         }
 
-                        return Ok(Output::Plain(lines.join("\n")));
+                        return Ok(output.into());
+                    } else {
+                        derived.lines.extend(derived.descriptors);
+                        return Ok(Output::Plain(derived.lines.join("\n")));
                     }
                 }
 
                 Ok(Output::Empty)
             }
+            Receiver::Scan {
+                rpc,
+                scan_range,
+                common_args:
+                    CommonArgs {
+                        account,
+                        json,
+                        seed_source,
+                        network,
+                    },
+                address_types: AddressTypesArg { address_types },
+                address_range:
+                    AddressRangeArgs {
+                        start_address_index,
+                        last_address_index,
+                    },
+                show_private_key,
+            } => {
+                let seed = get_seed_hex(&seed_source)?;
+
+                let accepted_addresses: HashSet<bip351::AddressType> =
+                    address_types.into_iter().map(|t| t.into()).collect();
+
+                let recipient = bip351::Recipient::from_seed(
+                    &secp,
+                    &seed,
+                    network.into(),
+                    account,
+                    accepted_addresses,
+                )?;
+
+                let range = index_range(start_address_index, last_address_index);
+
+                scan::scan(
+                    &secp,
+                    &recipient,
+                    &rpc,
+                    &scan_range,
+                    range,
+                    show_private_key,
+                    json,
+                )
+            }
+            Receiver::Sweep {
+                notification,
+                common_args:
+                    CommonArgs {
+                        account,
+                        json,
+                        seed_source,
+                        network,
+                    },
+                address_types: AddressTypesArg { address_types },
+                address_range,
+                esplora,
+                sweep: sweep_args,
+            } => {
+                let network: Network = network.into();
+                let bytes: Vec<u8> = FromHex::from_hex(&notification)?;
+                let script = if bytes.starts_with(b"PP") {
+                    Script::new_op_return(&bytes)
+                } else {
+                    Script::from(bytes)
+                };
+
+                let seed = get_seed_hex(&seed_source)?;
+
+                let accepted_addresses: HashSet<bip351::AddressType> =
+                    address_types.into_iter().map(|t| t.into()).collect();
+
+                let recipient = bip351::Recipient::from_seed(
+                    &secp,
+                    &seed,
+                    network,
+                    account,
+                    accepted_addresses,
+                )?;
+
+                let commitment = recipient
+                    .detect_notification(&secp, &script)
+                    .ok_or(Error::NotANotification)?;
+
+                sweep::sweep(
+                    &secp,
+                    &recipient,
+                    &commitment,
+                    &address_range,
+                    &esplora,
+                    network,
+                    account,
+                    &seed,
+                    &sweep_args,
+                    json,
+                )
+            }
+        }
+    }
+}
+
+/// Derive the stealth addresses for `range` from a detected `commitment`, in both the JSON and
+/// plain-text shapes used for a single decoded notification. Used during block scanning, where
+/// every notification is derived over the same fixed index range.
+fn decode_addresses_range(
+    secp: &Secp256k1<All>,
+    recipient: &bip351::Recipient,
+    commitment: &bip351::Commitment,
+    range: RangeInclusive<u64>,
+    show_private_key: bool,
+) -> Result<(Vec<json::JsonValue>, Vec<String>), Error> {
+    let output_capacity = range.end().saturating_add(1).saturating_sub(*range.start()) as usize;
+    let mut addresses = Vec::with_capacity(output_capacity);
+    let mut lines = Vec::with_capacity(output_capacity);
+
+    for c in range {
+        let (address, public_key, private_key) = recipient.key_info(secp, commitment, c)?;
+        if show_private_key {
+            addresses.push(json::object! {
+                address: address.to_string(),
+                index: c,
+                public_key: public_key.to_string(),
+                private_key: private_key.to_string(),
+            });
+            lines.push(format!("{c}: {address} {public_key} {private_key}"));
+        } else {
+            addresses.push(json::object! {
+                address: address.to_string(),
+                index: c,
+            });
+            lines.push(format!("{c}: {address}"));
+        }
+    }
+
+    Ok((addresses, lines))
+}
+
+/// The JSON entries, plain-text lines, and (optionally) output descriptors produced by deriving a
+/// range of stealth addresses, bundled together so callers don't have to thread a growing tuple.
+struct DerivedAddresses {
+    addresses: Vec<json::JsonValue>,
+    lines: Vec<String>,
+    descriptors: Vec<String>,
+}
+
+/// Derive the stealth addresses for `address_range` from a detected `commitment`, augmenting each
+/// with its on-chain balance/UTXOs when `esplora` has a configured endpoint. When `address_range`
+/// has no explicit end and a gap limit is set, derivation stops after that many consecutive
+/// indices show no activity instead of requiring `-f`.
+#[allow(clippy::too_many_arguments)]
+fn decode_addresses(
+    secp: &Secp256k1<All>,
+    recipient: &bip351::Recipient,
+    commitment: &bip351::Commitment,
+    address_range: &AddressRangeArgs,
+    esplora: &esplora::EsploraArgs,
+    show_private_key: bool,
+    show_descriptor: bool,
+) -> Result<DerivedAddresses, Error> {
+    let mut addresses = Vec::new();
+    let mut lines = Vec::new();
+    let mut descriptors = Vec::new();
+    let mut consecutive_unused = 0u32;
+    let mut c = address_range.start_address_index;
+
+    loop {
+        let (address, public_key, private_key) = recipient.key_info(secp, commitment, c)?;
+        let lookup = esplora.lookup(&address)?;
+
+        let mut entry = if show_private_key {
+            json::object! {
+                address: address.to_string(),
+                index: c,
+                public_key: public_key.to_string(),
+                private_key: private_key.to_string(),
+            }
+        } else {
+            json::object! {
+                address: address.to_string(),
+                index: c,
+            }
+        };
+
+        let mut line = if show_private_key {
+            format!("{c}: {address} {public_key} {private_key}")
+        } else {
+            format!("{c}: {address}")
+        };
+
+        if let Some(lookup) = &lookup {
+            lookup.merge_into(&mut entry);
+            line.push(' ');
+            line.push_str(&lookup.to_plain());
+        }
+
+        if show_descriptor {
+            if let Some(address_type) = address.address_type() {
+                let private_key = show_private_key.then_some(&private_key);
+                let desc = descriptor::for_key(address_type, &public_key, private_key);
+                descriptors.push(format!("{c}: {desc}"));
+            }
         }
+
+        addresses.push(entry);
+        lines.push(line);
+
+        match address_range.last_address_index {
+            Some(last) => {
+                if c >= last {
+                    break;
+                }
+            }
+            None => match esplora.gap_limit() {
+                Some(gap_limit) => {
+                    consecutive_unused = match &lookup {
+                        Some(lookup) if lookup.is_used() => 0,
+                        _ => consecutive_unused + 1,
+                    };
+                    if consecutive_unused >= gap_limit {
+                        break;
+                    }
+                }
+                None => break,
+            },
+        }
+
+        c += 1;
     }
+
+    Ok(DerivedAddresses {
+        addresses,
+        lines,
+        descriptors,
+    })
 }
 
 impl Sender {
@@ -267,40 +573,59 @@ impl Sender {
 
         match self {
             Sender::Notify {
-                common_args: CommonArgs { account, json },
+                common_args:
+                    CommonArgs {
+                        account,
+                        json,
+                        seed_source,
+                        network,
+                    },
                 recipient_index,
                 address_type: AddressTypeArg { address_type },
                 recipient_payment_code,
-                address_range:
-                    AddressRangeArgs {
-                        start_address_index,
-                        last_address_index,
-                    },
+                address_range,
+                esplora,
+                psbt,
+                descriptor: show_descriptor,
             } => {
+                let network: Network = network.into();
                 let recipient = bip351::PaymentCode::from_str(&recipient_payment_code)?;
+                if recipient.network() != network {
+                    return Err(Error::NetworkMismatch);
+                }
 
-                let seed = get_seed_hex()?;
+                let seed = get_seed_hex(&seed_source)?;
 
-                let sender = bip351::Sender::from_seed(&secp, &seed, Network::Bitcoin, account)?;
+                let sender = bip351::Sender::from_seed(&secp, &seed, network, account)?;
 
                 let (txout, commitment) =
                     sender.notify(&secp, &recipient, recipient_index, address_type.into())?;
 
-                let range = index_range(start_address_index, last_address_index);
-                let output_capacity =
-                    range.end().saturating_add(1).saturating_sub(*range.start()) as usize;
+                let (mut derived, raw_addresses) = notify_addresses(
+                    &secp,
+                    &sender,
+                    &commitment,
+                    &address_range,
+                    &esplora,
+                    show_descriptor,
+                )?;
 
-                if json {
-                    let mut addresses: Vec<json::JsonValue> = Vec::with_capacity(output_capacity);
-                    for c in range {
-                        let address = sender.address(&secp, &commitment, c)?;
-                        addresses.push(json::object! {
-                            address: address.to_string(),
-                            index: c,
-                        });
-                    }
+                let psbt_output = if psbt.requested() {
+                    let psbt = crate::psbt::notify_psbt(
+                        txout.script_pubkey.clone(),
+                        &raw_addresses,
+                        psbt.pay_amount_sat(),
+                    )?;
+                    Some((
+                        psbt.to_string(),
+                        bitcoin::consensus::encode::serialize(&psbt).to_hex(),
+                    ))
+                } else {
+                    None
+                };
 
-                    let output = json::object! {
+                if json {
+                    let mut output = json::object! {
                         receiver: json::object!{
                             payment_code: recipient_payment_code,
                             index: recipient_index,
@@ -314,31 +639,120 @@ impl Sender {
                             payload: txout.script_pubkey.to_bytes()[2..].to_hex(),
                             asm: txout.script_pubkey.asm(),
                         },
-                        addresses: addresses,
+                        addresses: derived.addresses,
                     };
 
+                    if let Some((psbt_base64, psbt_hex)) = psbt_output {
+                        output["psbt"] = psbt_base64.into();
+                        output["psbt_hex"] = psbt_hex.into();
+                    }
+
+                    if !derived.descriptors.is_empty() {
+                        output["descriptors"] = derived.descriptors.into();
+                    }
+
                     Ok(output.into())
                 } else {
-                    let mut lines: Vec<String> =
-                        Vec::with_capacity(output_capacity.saturating_add(1));
-
-                    lines.push(txout.script_pubkey.asm());
-                    for c in range {
-                        let address = sender.address(&secp, &commitment, c)?;
-                        lines.push(format!("{c}: {address}"));
+                    derived.lines.insert(0, txout.script_pubkey.asm());
+                    derived.lines.extend(derived.descriptors);
+                    if let Some((psbt_base64, _)) = psbt_output {
+                        derived.lines.push(psbt_base64);
                     }
+                    Ok(Output::Plain(derived.lines.join("\n")))
+                }
+            }
+        }
+    }
+}
+
+/// Derive the stealth addresses for `address_range` from a notification `commitment`, augmenting
+/// each with its on-chain balance/UTXOs when `esplora` has a configured endpoint. Mirrors
+/// `decode_addresses`, but for the sender side, which has no private key to show.
+#[allow(clippy::too_many_arguments)]
+fn notify_addresses(
+    secp: &Secp256k1<All>,
+    sender: &bip351::Sender,
+    commitment: &bip351::Commitment,
+    address_range: &AddressRangeArgs,
+    esplora: &esplora::EsploraArgs,
+    show_descriptor: bool,
+) -> Result<(DerivedAddresses, Vec<bitcoin::Address>), Error> {
+    let mut addresses = Vec::new();
+    let mut lines = Vec::new();
+    let mut raw_addresses = Vec::new();
+    let mut descriptors = Vec::new();
+    let mut consecutive_unused = 0u32;
+    let mut c = address_range.start_address_index;
+
+    loop {
+        let address = sender.address(secp, commitment, c)?;
+        let lookup = esplora.lookup(&address)?;
+
+        let mut entry = json::object! {
+            address: address.to_string(),
+            index: c,
+        };
+        let mut line = format!("{c}: {address}");
+
+        if let Some(lookup) = &lookup {
+            lookup.merge_into(&mut entry);
+            line.push(' ');
+            line.push_str(&lookup.to_plain());
+        }
+
+        if show_descriptor {
+            descriptors.push(format!("{c}: {}", descriptor::for_address(&address)));
+        }
 
-                    Ok(Output::Plain(lines.join("\n")))
+        addresses.push(entry);
+        lines.push(line);
+        raw_addresses.push(address);
+
+        match address_range.last_address_index {
+            Some(last) => {
+                if c >= last {
+                    break;
                 }
             }
+            None => match esplora.gap_limit() {
+                Some(gap_limit) => {
+                    consecutive_unused = match &lookup {
+                        Some(lookup) if lookup.is_used() => 0,
+                        _ => consecutive_unused + 1,
+                    };
+                    if consecutive_unused >= gap_limit {
+                        break;
+                    }
+                }
+                None => break,
+            },
         }
+
+        c += 1;
     }
+
+    Ok((
+        DerivedAddresses {
+            addresses,
+            lines,
+            descriptors,
+        },
+        raw_addresses,
+    ))
 }
 
-fn get_seed_hex() -> Result<Vec<u8>, Error> {
-    let seed_hex = SecUtf8::from(Password::new().with_prompt("Seed Hex").interact()?);
-    let seed: Vec<u8> = FromHex::from_hex(seed_hex.unsecure())?;
-    Ok(seed)
+/// Obtain the wallet seed from the operator, either as raw hex (used as decoded, at whatever length
+/// it has) or, when `seed_source` is `Mnemonic`, as a BIP39 mnemonic phrase and optional
+/// passphrase, which yields a 64-byte seed.
+fn get_seed_hex(seed_source: &SeedSource) -> Result<Vec<u8>, Error> {
+    match seed_source {
+        SeedSource::Hex => {
+            let seed_hex = SecUtf8::from(Password::new().with_prompt("Seed Hex").interact()?);
+            let seed: Vec<u8> = FromHex::from_hex(seed_hex.unsecure())?;
+            Ok(seed)
+        }
+        SeedSource::Mnemonic => mnemonic::seed_from_mnemonic(),
+    }
 }
 
 fn index_range(first_index: u64, last_index: Option<u64>) -> RangeInclusive<u64> {
@@ -431,7 +845,21 @@ enum Error {
     Bip32(bitcoin::util::bip32::Error),
     Dialoguer(std::io::Error),
     Hex(bitcoin::hashes::hex::Error),
+    Http(reqwest::Error),
+    IndexOutOfRange(u64),
+    InsufficientFunds,
+    Io(std::io::Error),
+    Json(json::Error),
+    Mnemonic(String),
+    NetworkMismatch,
+    NoSpendableUtxos,
+    NotANotification,
+    Psbt(bitcoin::util::psbt::Error),
     PrivatePayment(bip351::Error),
+    Rpc(bitcoincore_rpc::Error),
+    Secp(bitcoin::secp256k1::Error),
+    Sighash(bitcoin::util::sighash::Error),
+    UnsupportedAddressType,
 }
 
 impl From<bitcoin::util::address::Error> for Error {
@@ -463,3 +891,27 @@ impl From<bip351::Error> for Error {
         Self::PrivatePayment(e)
     }
 }
+
+impl From<bitcoincore_rpc::Error> for Error {
+    fn from(e: bitcoincore_rpc::Error) -> Self {
+        Self::Rpc(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+impl From<bitcoin::secp256k1::Error> for Error {
+    fn from(e: bitcoin::secp256k1::Error) -> Self {
+        Self::Secp(e)
+    }
+}
+
+impl From<bitcoin::util::sighash::Error> for Error {
+    fn from(e: bitcoin::util::sighash::Error) -> Self {
+        Self::Sighash(e)
+    }
+}
@@ -0,0 +1,23 @@
+use bip39::{Language, Mnemonic};
+use dialoguer::Password;
+use secstr::SecUtf8;
+
+use crate::Error;
+
+/// Prompt for a BIP39 mnemonic phrase and an optional passphrase, validate the phrase's checksum,
+/// and derive the 64-byte wallet seed via PBKDF2-HMAC-SHA512, returned in the same form as the
+/// seed `get_seed_hex` decodes from raw hex input.
+pub fn seed_from_mnemonic() -> Result<Vec<u8>, Error> {
+    let phrase = SecUtf8::from(Password::new().with_prompt("Mnemonic").interact()?);
+    let mnemonic = Mnemonic::parse_in_normalized(Language::English, phrase.unsecure())
+        .map_err(|e| Error::Mnemonic(e.to_string()))?;
+
+    let passphrase = SecUtf8::from(
+        Password::new()
+            .with_prompt("Mnemonic passphrase (press enter for none)")
+            .allow_empty_password(true)
+            .interact()?,
+    );
+
+    Ok(mnemonic.to_seed(passphrase.unsecure()).to_vec())
+}
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use bitcoin::secp256k1::{All, Secp256k1};
+use bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, Fingerprint};
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::{Address, Network, PackedLockTime, Script, Transaction, TxOut};
+use clap::Args;
+
+use crate::Error;
+
+/// Emit a notification (and optional payment outputs) as a PSBT alongside the bare scriptpubkey
+#[derive(Debug, Clone, Args)]
+pub struct PsbtArgs {
+    /// Also emit a BIP174 PSBT holding the notification output, alongside the bare scriptpubkey
+    #[arg(long, default_value_t = false)]
+    psbt: bool,
+    /// Amount in satoshis to pay each derived stealth address; requires --psbt
+    #[arg(long, requires = "psbt")]
+    pay: Option<u64>,
+}
+
+impl PsbtArgs {
+    pub fn requested(&self) -> bool {
+        self.psbt
+    }
+
+    pub fn pay_amount_sat(&self) -> Option<u64> {
+        self.pay
+    }
+}
+
+/// Build an unsigned, input-less PSBT holding the notification output and, when `pay_amount_sat`
+/// is set, one payment output per address in `addresses`.
+pub fn notify_psbt(
+    notification_script_pubkey: Script,
+    addresses: &[Address],
+    pay_amount_sat: Option<u64>,
+) -> Result<PartiallySignedTransaction, Error> {
+    let mut output = vec![TxOut {
+        value: 0,
+        script_pubkey: notification_script_pubkey,
+    }];
+
+    if let Some(amount) = pay_amount_sat {
+        output.extend(addresses.iter().map(|address| TxOut {
+            value: amount,
+            script_pubkey: address.script_pubkey(),
+        }));
+    }
+
+    let tx = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: Vec::new(),
+        output,
+    };
+
+    PartiallySignedTransaction::from_unsigned_tx(tx).map_err(Error::Psbt)
+}
+
+/// The BIP32 master fingerprint for `seed`, used to populate PSBT `bip32_derivation` fields for
+/// keys derived under `m/351'/0'/<account>'`.
+pub fn master_fingerprint(
+    secp: &Secp256k1<All>,
+    seed: &[u8],
+    network: Network,
+) -> Result<Fingerprint, Error> {
+    Ok(ExtendedPrivKey::new_master(network, seed)?.fingerprint(secp))
+}
+
+/// The derivation path recorded in PSBT `bip32_derivation` for a stealth address: the BIP351
+/// account path followed by the stealth commitment index. The index is not a true BIP32 child of
+/// the account key (stealth addresses are derived via ECDH tweak, not plain HD derivation), but
+/// recording it this way lets external wallets and signers identify which address an input
+/// belongs to.
+pub fn derivation_path(account: u32, index: u64) -> Result<DerivationPath, Error> {
+    let index = u32::try_from(index).map_err(|_| Error::IndexOutOfRange(index))?;
+
+    Ok(DerivationPath::from(vec![
+        ChildNumber::from_hardened_idx(351).expect("351 < 2^31"),
+        ChildNumber::from_hardened_idx(0).expect("0 < 2^31"),
+        ChildNumber::from_hardened_idx(account)?,
+        ChildNumber::from_normal_idx(index)?,
+    ]))
+}
+
+/// Record the BIP32 derivation metadata for `public_key` on a PSBT input.
+pub fn set_bip32_derivation(
+    psbt_input: &mut bitcoin::util::psbt::Input,
+    fingerprint: Fingerprint,
+    account: u32,
+    index: u64,
+    public_key: bitcoin::secp256k1::PublicKey,
+) -> Result<(), Error> {
+    let mut bip32_derivation = BTreeMap::new();
+    bip32_derivation.insert(public_key, (fingerprint, derivation_path(account, index)?));
+    psbt_input.bip32_derivation = bip32_derivation;
+    Ok(())
+}
+
+/// Record the BIP371 taproot key-path derivation metadata for `public_key` on a PSBT input,
+/// the taproot counterpart to `set_bip32_derivation`: the x-only internal key and its key origin,
+/// with no script-path leaves since stealth addresses are single-key P2TR outputs.
+pub fn set_taproot_key_origin(
+    secp: &Secp256k1<All>,
+    psbt_input: &mut bitcoin::util::psbt::Input,
+    fingerprint: Fingerprint,
+    account: u32,
+    index: u64,
+    public_key: bitcoin::secp256k1::PublicKey,
+) -> Result<(), Error> {
+    let (internal_key, _parity) = public_key.x_only_public_key(secp);
+
+    let mut tap_key_origins = BTreeMap::new();
+    tap_key_origins.insert(
+        internal_key,
+        (Vec::new(), (fingerprint, derivation_path(account, index)?)),
+    );
+    psbt_input.tap_internal_key = Some(internal_key);
+    psbt_input.tap_key_origins = tap_key_origins;
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use bip351::Recipient;
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::secp256k1::{All, Secp256k1};
+use bitcoin::Script;
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use clap::Args;
+
+use crate::{decode_addresses_range, Error, Output};
+
+/// Connection details for a bitcoind JSON-RPC endpoint
+#[derive(Debug, Clone, Args)]
+pub struct RpcArgs {
+    /// bitcoind RPC endpoint, e.g. http://127.0.0.1:8332
+    #[arg(long)]
+    rpc_url: String,
+    /// Path to bitcoind's .cookie file
+    #[arg(long, conflicts_with_all = ["rpc_user", "rpc_password"])]
+    rpc_cookie: Option<PathBuf>,
+    /// RPC username, used together with --rpc-password
+    #[arg(long, requires = "rpc_password")]
+    rpc_user: Option<String>,
+    /// RPC password, used together with --rpc-user
+    #[arg(long, requires = "rpc_user")]
+    rpc_password: Option<String>,
+}
+
+impl RpcArgs {
+    fn connect(&self) -> Result<Client, Error> {
+        let auth = match (&self.rpc_cookie, &self.rpc_user, &self.rpc_password) {
+            (Some(cookie), _, _) => Auth::CookieFile(cookie.clone()),
+            (None, Some(user), Some(password)) => Auth::UserPass(user.clone(), password.clone()),
+            _ => Auth::None,
+        };
+
+        Client::new(&self.rpc_url, auth).map_err(Error::Rpc)
+    }
+}
+
+/// Which blocks to scan, and where to persist progress for an incremental rescan
+#[derive(Debug, Clone, Args)]
+pub struct ScanRangeArgs {
+    /// First block height to scan
+    #[arg(long)]
+    from: u64,
+    /// Last block height to scan (inclusive); defaults to the current chain tip
+    #[arg(long)]
+    to: Option<u64>,
+    /// File storing the last completed height, so a later scan can resume from it
+    #[arg(long)]
+    resume_file: Option<PathBuf>,
+}
+
+/// Walk `scan_range` one block at a time over the bitcoind RPC connection in `rpc`, detecting
+/// notifications addressed to `recipient` and deriving the resulting stealth addresses over
+/// `address_range`, exactly as `Receiver::Decode` would for a single notification payload.
+#[allow(clippy::too_many_arguments)]
+pub fn scan(
+    secp: &Secp256k1<All>,
+    recipient: &Recipient,
+    rpc: &RpcArgs,
+    scan_range: &ScanRangeArgs,
+    address_range: RangeInclusive<u64>,
+    show_private_key: bool,
+    json: bool,
+) -> Result<Output, Error> {
+    let client = rpc.connect()?;
+
+    let resume_height = scan_range
+        .resume_file
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| contents.trim().parse::<u64>().ok());
+
+    let from = resume_height.map_or(scan_range.from, |h| {
+        h.saturating_add(1).max(scan_range.from)
+    });
+    let to = match scan_range.to {
+        Some(to) => to,
+        None => client.get_block_count()?,
+    };
+
+    let mut notifications = Vec::new();
+    let mut lines = Vec::new();
+
+    for height in from..=to {
+        eprintln!(
+            "scanning block {height} ({} remaining)",
+            to.saturating_sub(height)
+        );
+
+        let hash = client.get_block_hash(height)?;
+        let block = client.get_block(&hash)?;
+
+        for tx in &block.txdata {
+            for (vout, txout) in tx.output.iter().enumerate() {
+                if !is_notification_candidate(&txout.script_pubkey) {
+                    continue;
+                }
+
+                if let Some(commitment) = recipient.detect_notification(secp, &txout.script_pubkey)
+                {
+                    let (addresses, address_lines) = decode_addresses_range(
+                        secp,
+                        recipient,
+                        &commitment,
+                        address_range.clone(),
+                        show_private_key,
+                    )?;
+
+                    if json {
+                        notifications.push(json::object! {
+                            height: height,
+                            txid: tx.txid().to_string(),
+                            vout: vout as u32,
+                            notification: json::object! {
+                                scriptpubkey: txout.script_pubkey.to_hex(),
+                                payload: txout.script_pubkey.to_bytes()[2..].to_hex(),
+                                asm: txout.script_pubkey.asm(),
+                            },
+                            addresses: addresses,
+                        });
+                    } else {
+                        lines.push(format!("block {height} {}:{vout}", tx.txid()));
+                        lines.extend(address_lines);
+                    }
+                }
+            }
+        }
+
+        if let Some(resume_file) = &scan_range.resume_file {
+            fs::write(resume_file, height.to_string()).map_err(Error::Io)?;
+        }
+    }
+
+    if json {
+        Ok(json::object! {
+            receiver: json::object! {
+                payment_code: recipient.payment_code().to_string(),
+            },
+            scanned: json::object! { from: from, to: to },
+            notifications: notifications,
+        }
+        .into())
+    } else {
+        Ok(Output::Plain(lines.join("\n")))
+    }
+}
+
+/// A quick pre-filter so we only pay for a full `detect_notification` call on scripts that could
+/// plausibly be a BIP351 notification: `OP_RETURN` followed by a push starting with `PP`.
+fn is_notification_candidate(script: &Script) -> bool {
+    script.is_op_return()
+        && script
+            .instructions()
+            .nth(1)
+            .and_then(|instruction| instruction.ok())
+            .map(|instruction| match instruction {
+                Instruction::PushBytes(bytes) => bytes.starts_with(b"PP"),
+                Instruction::Op(_) => false,
+            })
+            .unwrap_or(false)
+}
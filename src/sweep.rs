@@ -0,0 +1,299 @@
+use std::str::FromStr;
+
+use bip351::{Commitment, Recipient};
+use bitcoin::blockdata::script::Builder;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::schnorr::TapTweak;
+use bitcoin::secp256k1::{All, KeyPair, Message, Secp256k1};
+use bitcoin::util::sighash::{EcdsaSighashType, Prevouts, SchnorrSighashType, SighashCache};
+use bitcoin::{
+    Address, AddressType, Network, OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn,
+    TxOut, Txid, Witness,
+};
+use clap::Args;
+
+use crate::esplora::EsploraArgs;
+use crate::{AddressRangeArgs, Error, Output};
+
+/// Destination and feerate for a sweep of detected stealth UTXOs
+#[derive(Debug, Clone, Args)]
+pub struct SweepArgs {
+    /// Address to send the swept funds to
+    #[arg(long)]
+    destination: String,
+    /// Feerate in sat/vB used to compute the transaction fee
+    #[arg(long)]
+    feerate: f64,
+    /// Emit an unsigned PSBT with per-input BIP32 derivation metadata instead of a signed transaction
+    #[arg(long, default_value_t = false)]
+    psbt: bool,
+}
+
+struct SpendableInput {
+    outpoint: OutPoint,
+    value_sat: u64,
+    address: Address,
+    public_key: bitcoin::PublicKey,
+    private_key: bitcoin::PrivateKey,
+    index: u64,
+}
+
+/// Derive the stealth addresses over `address_range`, find every UTXO on them via `esplora`, and
+/// produce a transaction spending all of them to `args.destination` - fully signed, or, when
+/// `args.psbt` is set, an unsigned PSBT with per-input BIP32 derivation metadata.
+#[allow(clippy::too_many_arguments)]
+pub fn sweep(
+    secp: &Secp256k1<All>,
+    recipient: &Recipient,
+    commitment: &Commitment,
+    address_range: &AddressRangeArgs,
+    esplora: &EsploraArgs,
+    network: Network,
+    account: u32,
+    seed: &[u8],
+    args: &SweepArgs,
+    json: bool,
+) -> Result<Output, Error> {
+    let destination = Address::from_str(&args.destination)?;
+    if destination.network != network {
+        return Err(Error::NetworkMismatch);
+    }
+
+    let inputs = find_spendable_inputs(secp, recipient, commitment, address_range, esplora)?;
+    if inputs.is_empty() {
+        return Err(Error::NoSpendableUtxos);
+    }
+
+    let total_in_sat: u64 = inputs.iter().map(|i| i.value_sat).sum();
+    let vsize = estimate_vsize(&inputs, &destination);
+    let fee_sat = (args.feerate * vsize as f64).ceil() as u64;
+    let send_sat = total_in_sat
+        .checked_sub(fee_sat)
+        .ok_or(Error::InsufficientFunds)?;
+
+    let mut tx = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: inputs
+            .iter()
+            .map(|input| TxIn {
+                previous_output: input.outpoint,
+                script_sig: Script::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: send_sat,
+            script_pubkey: destination.script_pubkey(),
+        }],
+    };
+
+    let (txid, hex, is_psbt) = if args.psbt {
+        let fingerprint = crate::psbt::master_fingerprint(secp, seed, network)?;
+        let mut psbt =
+            bitcoin::util::psbt::PartiallySignedTransaction::from_unsigned_tx(tx.clone())
+                .map_err(Error::Psbt)?;
+
+        for (psbt_input, input) in psbt.inputs.iter_mut().zip(&inputs) {
+            psbt_input.witness_utxo = Some(TxOut {
+                value: input.value_sat,
+                script_pubkey: input.address.script_pubkey(),
+            });
+
+            if input.address.address_type() == Some(AddressType::P2tr) {
+                crate::psbt::set_taproot_key_origin(
+                    secp,
+                    psbt_input,
+                    fingerprint,
+                    account,
+                    input.index,
+                    input.public_key.inner,
+                )?;
+            } else {
+                crate::psbt::set_bip32_derivation(
+                    psbt_input,
+                    fingerprint,
+                    account,
+                    input.index,
+                    input.public_key.inner,
+                )?;
+            }
+        }
+
+        (tx.txid(), psbt.to_string(), true)
+    } else {
+        sign_inputs(secp, &inputs, &mut tx)?;
+        (
+            tx.txid(),
+            bitcoin::consensus::encode::serialize(&tx).to_hex(),
+            false,
+        )
+    };
+
+    if json {
+        Ok(json::object! {
+            destination: args.destination.clone(),
+            feerate: args.feerate,
+            inputs: inputs.iter().map(|i| json::object! {
+                outpoint: i.outpoint.to_string(),
+                value_sat: i.value_sat,
+                address: i.address.to_string(),
+            }).collect::<Vec<_>>(),
+            total_in_sat: total_in_sat,
+            fee_sat: fee_sat,
+            send_sat: send_sat,
+            txid: txid.to_string(),
+            psbt: is_psbt,
+            hex: hex,
+        }
+        .into())
+    } else {
+        Ok(Output::Plain(format!("{txid}\n{hex}")))
+    }
+}
+
+fn find_spendable_inputs(
+    secp: &Secp256k1<All>,
+    recipient: &Recipient,
+    commitment: &Commitment,
+    address_range: &AddressRangeArgs,
+    esplora: &EsploraArgs,
+) -> Result<Vec<SpendableInput>, Error> {
+    let mut inputs = Vec::new();
+    let mut consecutive_unused = 0u32;
+    let mut index = address_range.start_address_index;
+
+    loop {
+        let (address, public_key, private_key) = recipient.key_info(secp, commitment, index)?;
+        let lookup = esplora.lookup(&address)?;
+
+        if let Some(lookup) = &lookup {
+            for utxo in &lookup.utxos {
+                inputs.push(SpendableInput {
+                    outpoint: OutPoint::new(Txid::from_str(&utxo.txid)?, utxo.vout),
+                    value_sat: utxo.value_sat,
+                    address: address.clone(),
+                    public_key,
+                    private_key,
+                    index,
+                });
+            }
+        }
+
+        consecutive_unused = match &lookup {
+            Some(lookup) if lookup.is_used() => 0,
+            _ => consecutive_unused + 1,
+        };
+
+        match address_range.last_address_index {
+            Some(last) => {
+                if index >= last {
+                    break;
+                }
+            }
+            None => match esplora.gap_limit() {
+                Some(gap_limit) if consecutive_unused >= gap_limit => break,
+                Some(_) => {}
+                None => break,
+            },
+        }
+
+        index += 1;
+    }
+
+    Ok(inputs)
+}
+
+fn sign_inputs(
+    secp: &Secp256k1<All>,
+    inputs: &[SpendableInput],
+    tx: &mut Transaction,
+) -> Result<(), Error> {
+    let prevouts: Vec<TxOut> = inputs
+        .iter()
+        .map(|input| TxOut {
+            value: input.value_sat,
+            script_pubkey: input.address.script_pubkey(),
+        })
+        .collect();
+
+    for (i, input) in inputs.iter().enumerate() {
+        match input.address.address_type() {
+            Some(AddressType::P2wpkh) => {
+                let script_code = p2pkh_script_code(&input.public_key);
+                let sighash = SighashCache::new(&mut *tx).segwit_signature_hash(
+                    i,
+                    &script_code,
+                    input.value_sat,
+                    EcdsaSighashType::All,
+                )?;
+                let msg = Message::from_slice(sighash.as_ref())?;
+                let sig = secp.sign_ecdsa(&msg, &input.private_key.inner);
+                let mut sig_bytes = sig.serialize_der().to_vec();
+                sig_bytes.push(EcdsaSighashType::All as u8);
+                tx.input[i].witness =
+                    Witness::from_vec(vec![sig_bytes, input.public_key.to_bytes()]);
+            }
+            Some(AddressType::P2pkh) => {
+                let script_code = p2pkh_script_code(&input.public_key);
+                let sighash = SighashCache::new(&mut *tx).legacy_signature_hash(
+                    i,
+                    &script_code,
+                    EcdsaSighashType::All.to_u32(),
+                )?;
+                let msg = Message::from_slice(sighash.as_ref())?;
+                let sig = secp.sign_ecdsa(&msg, &input.private_key.inner);
+                let mut sig_bytes = sig.serialize_der().to_vec();
+                sig_bytes.push(EcdsaSighashType::All as u8);
+                tx.input[i].script_sig = Builder::new()
+                    .push_slice(&sig_bytes)
+                    .push_slice(&input.public_key.to_bytes())
+                    .into_script();
+            }
+            Some(AddressType::P2tr) => {
+                let sighash = SighashCache::new(&mut *tx).taproot_key_spend_signature_hash(
+                    i,
+                    &Prevouts::All(&prevouts),
+                    SchnorrSighashType::Default,
+                )?;
+                let msg = Message::from_slice(sighash.as_ref())?;
+                let keypair = KeyPair::from_secret_key(secp, &input.private_key.inner);
+                let tweaked_keypair = keypair.tap_tweak(secp, None).to_inner();
+                let sig = secp.sign_schnorr(&msg, &tweaked_keypair);
+                tx.input[i].witness = Witness::from_vec(vec![sig.as_ref().to_vec()]);
+            }
+            _ => return Err(Error::UnsupportedAddressType),
+        }
+    }
+
+    Ok(())
+}
+
+/// The P2PKH script for `public_key`'s hash, used both as the P2PKH scriptPubKey and as the BIP143
+/// scriptCode when spending the equivalent P2WPKH output.
+fn p2pkh_script_code(public_key: &bitcoin::PublicKey) -> Script {
+    Script::new_p2pkh(&public_key.pubkey_hash())
+}
+
+/// A conservative vsize estimate so the fee can be computed before the transaction is signed.
+fn estimate_vsize(inputs: &[SpendableInput], destination: &Address) -> u64 {
+    const OVERHEAD_VBYTES: u64 = 11;
+
+    let input_vbytes: u64 = inputs
+        .iter()
+        .map(|input| match input.address.address_type() {
+            Some(AddressType::P2wpkh) => 68,
+            Some(AddressType::P2tr) => 58,
+            _ => 148,
+        })
+        .sum();
+
+    let output_vbytes = match destination.address_type() {
+        Some(AddressType::P2wpkh) => 31,
+        Some(AddressType::P2tr | AddressType::P2wsh) => 43,
+        _ => 34,
+    };
+
+    OVERHEAD_VBYTES + input_vbytes + output_vbytes
+}